@@ -9,11 +9,29 @@ use ash::{
         CommandBufferUsageFlags,
         CommandPoolCreateFlags,
         CommandBufferBeginInfo,
+        CommandBufferInheritanceInfo,
+        BufferCopy,
+        BufferImageCopy,
+        ClearColorValue,
+        DescriptorSet,
+        DeviceSize,
+        Image,
+        ImageLayout,
+        ImageSubresourceRange,
+        Pipeline,
+        PipelineBindPoint,
+        PipelineLayout,
+        RenderPassBeginInfo,
+        ShaderStageFlags,
+        SubpassContents,
     },
 };
 use relevant::Relevant;
 
-use crate::family::FamilyIndex;
+use crate::{
+    capability::{Compute, Graphics, Supports, Transfer},
+    family::FamilyIndex,
+};
 
 /// Command buffers of this level can be submitted to the command queues.
 #[derive(Clone, Copy, Debug, Default)]
@@ -72,6 +90,31 @@ impl Reset for NoIndividualReset {
     }
 }
 
+/// This flag hints that buffers from the pool are short-lived.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Transient;
+
+/// This flag specify that buffers from the pool are not necessarily short-lived.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NonTransient;
+
+/// Specify flags contributed to command pool creation by the transient hint.
+pub trait Transience: Copy {
+    fn flags(&self) -> CommandPoolCreateFlags;
+}
+
+impl Transience for Transient {
+    fn flags(&self) -> CommandPoolCreateFlags {
+        CommandPoolCreateFlags::TRANSIENT
+    }
+}
+
+impl Transience for NonTransient {
+    fn flags(&self) -> CommandPoolCreateFlags {
+        CommandPoolCreateFlags::empty()
+    }
+}
+
 /// Command buffer state in which all buffers start.
 /// Resetting also moves buffer to this state.
 #[derive(Clone, Copy, Debug, Default)]
@@ -146,6 +189,12 @@ impl Usage for MultiShot<SimultaneousUse> {
     }
 }
 
+impl Usage for RenderPassContinue {
+    fn flags(&self) -> CommandBufferUsageFlags {
+        CommandBufferUsageFlags::RENDER_PASS_CONTINUE
+    }
+}
+
 /// Command buffer wrapper.
 /// This wrapper defines state with usage, level and ability to be individually reset at type level.
 /// This way many methods become safe.
@@ -252,11 +301,42 @@ impl<C, R> Buffer<C, InitialState, PrimaryLevel, R> {
     }
 }
 
-impl<C, U, R> Buffer<C, RecordingState<U>, PrimaryLevel, R> {
+impl<C, R> Buffer<C, InitialState, SecondaryLevel, R> {
+    /// Begin recording secondary command buffer.
+    ///
+    /// # Parameters
+    ///
+    /// `usage` - specifies usage of the command buffer. Possible types are `OneShot`, `MultiShot`, `RenderPassContinue`.
+    /// Supplying `RenderPassContinue` sets `CommandBufferUsageFlags::RENDER_PASS_CONTINUE`.
+    /// `inheritance` - render pass, subpass index, framebuffer and occlusion query flags this buffer inherits.
+    pub fn begin<U>(
+        self,
+        usage: U,
+        inheritance: CommandBufferInheritanceInfo,
+        device: &impl DeviceV1_0,
+    ) -> Buffer<C, RecordingState<U>, SecondaryLevel, R>
+    where
+        U: Usage,
+    {
+        unsafe {
+            device.begin_command_buffer(
+                self.raw,
+                &CommandBufferBeginInfo::builder()
+                    .flags(usage.flags())
+                    .inheritance_info(&inheritance)
+                    .build()
+            ).expect("Panic on OOM");
+
+            self.change_state(|_| RecordingState(usage))
+        }
+    }
+}
+
+impl<C, U, L, R> Buffer<C, RecordingState<U>, L, R> {
     /// Finish recording command buffer.
     ///
     /// # Parameters
-    pub fn finish(self, device: &impl DeviceV1_0) -> Buffer<C, ExecutableState<U>, PrimaryLevel, R>
+    pub fn finish(self, device: &impl DeviceV1_0) -> Buffer<C, ExecutableState<U>, L, R>
     where
         U: Usage,
     {
@@ -268,15 +348,239 @@ impl<C, U, R> Buffer<C, RecordingState<U>, PrimaryLevel, R> {
     }
 }
 
+impl<C, U, R> Buffer<C, RecordingState<U>, PrimaryLevel, R> {
+    /// Record execution of secondary command buffers.
+    ///
+    /// # Parameters
+    ///
+    /// `submits` - secondary `Submit` handles to replay. They must refer to secondary buffers
+    /// allocated from the same family as this primary buffer.
+    pub fn execute_commands(
+        &mut self,
+        submits: impl IntoIterator<Item = impl Borrow<Submit<SecondaryLevel>>>,
+        device: &impl DeviceV1_0,
+    ) {
+        let buffers = submits.into_iter().map(|submit| {
+            let submit = submit.borrow();
+            assert_eq!(submit.family(), self.family);
+            submit.raw()
+        }).collect::<Vec<_>>();
+
+        unsafe {
+            device.cmd_execute_commands(self.raw, &buffers);
+        }
+    }
+}
+
+impl<C, U, L, R> Buffer<C, RecordingState<U>, L, R>
+where
+    C: Supports<Compute>,
+{
+    /// Bind compute pipeline.
+    pub fn bind_compute_pipeline(&mut self, pipeline: Pipeline, device: &impl DeviceV1_0) {
+        unsafe {
+            device.cmd_bind_pipeline(self.raw, PipelineBindPoint::COMPUTE, pipeline);
+        }
+    }
+
+    /// Bind descriptor sets to the compute bind point.
+    pub fn bind_compute_descriptor_sets(
+        &mut self,
+        layout: PipelineLayout,
+        first_set: u32,
+        sets: &[DescriptorSet],
+        dynamic_offsets: &[u32],
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_bind_descriptor_sets(
+                self.raw,
+                PipelineBindPoint::COMPUTE,
+                layout,
+                first_set,
+                sets,
+                dynamic_offsets,
+            );
+        }
+    }
+
+    /// Dispatch compute work groups.
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32, device: &impl DeviceV1_0) {
+        unsafe {
+            device.cmd_dispatch(self.raw, x, y, z);
+        }
+    }
+
+    /// Dispatch compute work groups with parameters read from a buffer.
+    pub fn dispatch_indirect(
+        &mut self,
+        buffer: ash::vk::Buffer,
+        offset: DeviceSize,
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_dispatch_indirect(self.raw, buffer, offset);
+        }
+    }
+
+    /// Update push constants for the compute bind point.
+    pub fn push_compute_constants(
+        &mut self,
+        layout: PipelineLayout,
+        offset: u32,
+        constants: &[u8],
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_push_constants(
+                self.raw,
+                layout,
+                ShaderStageFlags::COMPUTE,
+                offset,
+                constants,
+            );
+        }
+    }
+}
+
+impl<C, U, L, R> Buffer<C, RecordingState<U>, L, R>
+where
+    C: Supports<Graphics>,
+{
+    /// Bind graphics pipeline.
+    pub fn bind_graphics_pipeline(&mut self, pipeline: Pipeline, device: &impl DeviceV1_0) {
+        unsafe {
+            device.cmd_bind_pipeline(self.raw, PipelineBindPoint::GRAPHICS, pipeline);
+        }
+    }
+
+    /// Clear color image outside of a render pass.
+    pub fn clear_image(
+        &mut self,
+        image: Image,
+        layout: ImageLayout,
+        color: ClearColorValue,
+        ranges: &[ImageSubresourceRange],
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_clear_color_image(self.raw, image, layout, &color, ranges);
+        }
+    }
+
+    /// Draw.
+    pub fn draw(
+        &mut self,
+        vertices: u32,
+        instances: u32,
+        first_vertex: u32,
+        first_instance: u32,
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_draw(self.raw, vertices, instances, first_vertex, first_instance);
+        }
+    }
+
+    /// Draw indexed.
+    pub fn draw_indexed(
+        &mut self,
+        indices: u32,
+        instances: u32,
+        first_index: u32,
+        vertex_offset: i32,
+        first_instance: u32,
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_draw_indexed(
+                self.raw,
+                indices,
+                instances,
+                first_index,
+                vertex_offset,
+                first_instance,
+            );
+        }
+    }
+}
+
+impl<C, U, R> Buffer<C, RecordingState<U>, PrimaryLevel, R>
+where
+    C: Supports<Graphics>,
+{
+    /// Begin render pass.
+    pub fn begin_render_pass(
+        &mut self,
+        info: &RenderPassBeginInfo,
+        contents: SubpassContents,
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_begin_render_pass(self.raw, info, contents);
+        }
+    }
+}
+
+impl<C, U, L, R> Buffer<C, RecordingState<U>, L, R>
+where
+    C: Supports<Transfer>,
+{
+    /// Copy regions between buffers.
+    pub fn copy_buffer(
+        &mut self,
+        src: ash::vk::Buffer,
+        dst: ash::vk::Buffer,
+        regions: &[BufferCopy],
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_copy_buffer(self.raw, src, dst, regions);
+        }
+    }
+
+    /// Copy regions from a buffer into an image.
+    pub fn copy_buffer_to_image(
+        &mut self,
+        src: ash::vk::Buffer,
+        dst: Image,
+        layout: ImageLayout,
+        regions: &[BufferImageCopy],
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_copy_buffer_to_image(self.raw, src, dst, layout, regions);
+        }
+    }
+
+    /// Fill a range of a buffer with a 32-bit value.
+    pub fn fill_buffer(
+        &mut self,
+        buffer: ash::vk::Buffer,
+        offset: DeviceSize,
+        size: DeviceSize,
+        data: u32,
+        device: &impl DeviceV1_0,
+    ) {
+        unsafe {
+            device.cmd_fill_buffer(self.raw, buffer, offset, size, data);
+        }
+    }
+}
+
 /// Structure contains command buffer ready for submission.
+/// Carries the buffer's `Level` so it can only be fed to the API that
+/// matches how the device expects to consume it: `execute_commands` requires
+/// `Submit<SecondaryLevel>`, while queue submission requires `Submit<PrimaryLevel>`.
 #[derive(Debug)]
 #[allow(missing_copy_implementations)]
-pub struct Submit {
+pub struct Submit<L> {
     raw: CommandBuffer,
     family: FamilyIndex,
+    level: L,
 }
 
-impl Submit {
+impl<L> Submit<L> {
     /// Get family this submit is associated with.
     pub fn family(&self) -> FamilyIndex {
         self.family
@@ -288,12 +592,16 @@ impl Submit {
     }
 }
 
-impl<C, R> Buffer<C, ExecutableState<OneShot>, PrimaryLevel, R> {
+impl<C, L, R> Buffer<C, ExecutableState<OneShot>, L, R> {
     /// produce `Submit` object that can be used to populate submission.
     pub fn submit_once(self) -> (
-        Submit,
-        Buffer<C, PendingState<InvalidState>, PrimaryLevel, R>,
-    ) {
+        Submit<L>,
+        Buffer<C, PendingState<InvalidState>, L, R>,
+    )
+    where
+        L: Level,
+    {
+        let level = self.level;
         let buffer = unsafe {
 
             self.change_state(|_| PendingState(InvalidState))
@@ -302,18 +610,23 @@ impl<C, R> Buffer<C, ExecutableState<OneShot>, PrimaryLevel, R> {
         let submit = Submit {
             raw: buffer.raw,
             family: buffer.family,
+            level,
         };
 
         (submit, buffer)
     }
 }
 
-impl<C, S, R> Buffer<C, ExecutableState<MultiShot<S>>, PrimaryLevel, R> {
+impl<C, S, L, R> Buffer<C, ExecutableState<MultiShot<S>>, L, R> {
     /// Produce `Submit` object that can be used to populate submission.
     pub fn submit(self) -> (
-        Submit,
-        Buffer<C, PendingState<ExecutableState<MultiShot<S>>>, PrimaryLevel, R>,
-    ) {
+        Submit<L>,
+        Buffer<C, PendingState<ExecutableState<MultiShot<S>>>, L, R>,
+    )
+    where
+        L: Level,
+    {
+        let level = self.level;
         let buffer = unsafe {
             self.change_state(|state| PendingState(state))
         };
@@ -321,6 +634,7 @@ impl<C, S, R> Buffer<C, ExecutableState<MultiShot<S>>, PrimaryLevel, R> {
         let submit = Submit {
             raw: buffer.raw,
             family: buffer.family,
+            level,
         };
 
         (submit, buffer)
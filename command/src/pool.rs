@@ -1,11 +1,13 @@
 //! Pool module docs.
 
+use std::{any::Any, sync::Arc};
+
 use ash::{
     version::DeviceV1_0,
-    vk::{CommandBuffer, CommandPool, QueueFlags, CommandBufferAllocateInfo},
+    vk::{CommandBuffer, CommandBufferResetFlags, CommandPool, CommandPoolCreateFlags, Fence, QueueFlags, CommandBufferAllocateInfo},
 };
 
-use failure::Error;
+use failure::{Error, Fail};
 use relevant::Relevant;
 
 use crate::{
@@ -14,41 +16,143 @@ use crate::{
     family::FamilyIndex
 };
 
+/// Error returned when a pool reset is requested while some of its buffers are
+/// still pending execution on the device.
+#[derive(Clone, Copy, Debug, Fail)]
+#[fail(display = "Can't reset pool with {} buffers still pending", pending)]
+pub struct SynchronizationError {
+    /// Number of buffers still pending on the device.
+    pub pending: usize,
+}
+
 /// Simple pool wrapper.
 /// Doesn't provide any guarantees.
 /// Wraps raw buffers into `Buffer`.
+/// Keeps resources referenced by recorded buffers alive until the pool is reset.
 #[derive(Debug)]
-pub struct Pool<C = QueueFlags, R = NoIndividualReset> {
+pub struct Pool<C = QueueFlags, R = NoIndividualReset, T = NonTransient> {
     raw: CommandPool,
     capability: C,
     reset: R,
+    transient: T,
     family: FamilyIndex,
+    resources: Vec<Arc<dyn Any + Send + Sync>>,
+    pending: usize,
     relevant: Relevant,
 }
 
-impl<C, R> Pool<C, R>
+impl<C, R, T> Pool<C, R, T>
 where
     C: Capability,
     R: Reset,
+    T: Transience,
 {
     /// Wrap raw command pool.
-    /// 
+    ///
     /// # Safety
-    /// 
+    ///
     /// * `raw` must be valid command pool handle.
     /// * The command pool must be created for specified `family` index.
     /// * `capability` must be subset of capabilites of the `family` the pool was created for.
     /// * if `reset` is `IndividualReset` the pool must be created with individual command buffer reset flag set.
-    pub unsafe fn from_raw(raw: CommandPool, capability: C, reset: R, family: FamilyIndex) -> Self {
+    /// * if `transient` is `Transient` the pool must be created with the transient flag set.
+    pub unsafe fn from_raw(raw: CommandPool, capability: C, reset: R, transient: T, family: FamilyIndex) -> Self {
         Pool {
             raw,
             capability,
             reset,
+            transient,
             family,
+            resources: Vec::new(),
+            pending: 0,
             relevant: Relevant,
         }
     }
 
+    /// Flags a command pool must be created with to back this wrapper,
+    /// combining the individual-reset and transient hints.
+    pub fn create_flags(&self) -> CommandPoolCreateFlags {
+        self.reset.flags() | self.transient.flags()
+    }
+
+    /// Keep `resource` alive until the pool is reset.
+    /// Recorded buffers that reference a resource must hold it here so it can't
+    /// be freed while the device may still read from it.
+    pub fn hold(&mut self, resource: Arc<dyn Any + Send + Sync>) {
+        self.resources.push(resource);
+    }
+
+    /// Note that a `Submit` referencing buffers from this pool was produced.
+    /// Keeps [`reset`](#method.reset) from succeeding until the matching
+    /// [`completed`](#method.completed) call.
+    /// Private: called only by [`submit_once`](#method.submit_once) and [`submit`](#method.submit).
+    /// A `Submit` produced directly through `Buffer::submit_once` or `Buffer::submit`
+    /// is not counted here.
+    fn submitted(&mut self) {
+        self.pending += 1;
+    }
+
+    /// Note that a previously submitted buffer completed execution.
+    /// Private: called by [`complete`](#method.complete) and by
+    /// `OwningPool::recycle` once a submitted buffer's fence has signaled.
+    fn completed(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+    }
+
+    /// Number of buffers from this pool still pending execution.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Produce a `Submit` from a one-shot buffer allocated from this pool,
+    /// marking the pool as having a buffer pending completion.
+    /// See [`Buffer::submit_once`](../buffer/struct.Buffer.html#method.submit_once).
+    pub fn submit_once<L>(
+        &mut self,
+        buffer: Buffer<C, ExecutableState<OneShot>, L, R>,
+    ) -> (Submit<L>, Buffer<C, PendingState<InvalidState>, L, R>)
+    where
+        L: Level,
+    {
+        let (submit, buffer) = buffer.submit_once();
+        self.submitted();
+        (submit, buffer)
+    }
+
+    /// Produce a `Submit` from a multi-shot buffer allocated from this pool,
+    /// marking the pool as having a buffer pending completion.
+    /// See [`Buffer::submit`](../buffer/struct.Buffer.html#method.submit).
+    pub fn submit<S, L>(
+        &mut self,
+        buffer: Buffer<C, ExecutableState<MultiShot<S>>, L, R>,
+    ) -> (Submit<L>, Buffer<C, PendingState<ExecutableState<MultiShot<S>>>, L, R>)
+    where
+        L: Level,
+    {
+        let (submit, buffer) = buffer.submit();
+        self.submitted();
+        (submit, buffer)
+    }
+
+    /// Mark `buffer` as complete, releasing the pending count it was holding
+    /// since [`submit_once`](#method.submit_once)/[`submit`](#method.submit).
+    /// See [`Buffer::complete`](../buffer/struct.Buffer.html#method.complete).
+    ///
+    /// # Safety
+    ///
+    /// * Commands recorded to this buffer must be complete, as required by
+    /// [`Buffer::complete`](../buffer/struct.Buffer.html#method.complete).
+    /// * `buffer` must have been produced by [`submit_once`](#method.submit_once)
+    /// or [`submit`](#method.submit) on this exact `Pool` instance. `pending` is a
+    /// bare counter with no per-buffer identity, so completing a buffer that was
+    /// submitted from a different pool (even one of the same `C`/`R`) silently
+    /// decrements the wrong pool's count and can let its [`reset`](#method.reset)
+    /// succeed while that buffer is still pending on the device.
+    pub unsafe fn complete<N, L>(&mut self, buffer: Buffer<C, PendingState<N>, L, R>) -> Buffer<C, N, L, R> {
+        self.completed();
+        buffer.complete()
+    }
+
     /// Allocate new command buffers.
     pub fn allocate_buffers<L: Level>(
         &mut self,
@@ -95,15 +199,19 @@ where
         }
     }
 
-    /// Reset all buffers of this pool.
-    /// 
-    /// # Safety
-    /// 
-    /// All buffers allocated from this pool must be marked reset.
-    /// See [`Buffer::mark_reset`](struct.Buffer.html#method.mark_reset)
-    pub unsafe fn reset(&mut self, device: &impl DeviceV1_0) {
-        device.reset_command_pool(self.raw, Default::default())
-            .expect("Panic if OOM");
+    /// Reset all buffers of this pool and drop every held resource.
+    /// Fails without touching the pool if any buffer is still pending execution;
+    /// see [`submitted`](#method.submitted) and [`completed`](#method.completed).
+    pub fn reset(&mut self, device: &impl DeviceV1_0) -> Result<(), SynchronizationError> {
+        if self.pending > 0 {
+            return Err(SynchronizationError { pending: self.pending });
+        }
+        unsafe {
+            device.reset_command_pool(self.raw, Default::default())
+                .expect("Panic if OOM");
+        }
+        self.resources.clear();
+        Ok(())
     }
 
     /// Dispose of command pool.
@@ -117,9 +225,9 @@ where
     }
 }
 
-impl<R> Pool<QueueFlags, R> {
+impl<R, T> Pool<QueueFlags, R, T> {
     /// Convert capability level
-    pub fn from_flags<C>(self) -> Result<Pool<C, R>, Self>
+    pub fn from_flags<C>(self) -> Result<Pool<C, R, T>, Self>
     where
         C: Capability,
     {
@@ -128,7 +236,10 @@ impl<R> Pool<QueueFlags, R> {
                 raw: self.raw,
                 capability,
                 reset: self.reset,
+                transient: self.transient,
                 family: self.family,
+                resources: self.resources,
+                pending: self.pending,
                 relevant: self.relevant,
             })
         } else {
@@ -139,44 +250,47 @@ impl<R> Pool<QueueFlags, R> {
 
 /// Command pool that owns allocated buffers.
 /// It can be used to borrow buffers one by one.
-/// All buffers will be reset together via pool.
-/// Prior reset user must ensure all buffers are complete.
+/// With `IndividualReset`, buffers submitted through [`submit`](#method.submit)
+/// are recycled back onto a free list by [`recycle`](#method.recycle) once the
+/// fence they were submitted with is signaled, so a steady-state frame loop
+/// allocates nothing.
 #[derive(Debug)]
-pub struct OwningPool<C = QueueFlags, L = PrimaryLevel> {
-    inner: Pool<C>,
+pub struct OwningPool<C = QueueFlags, L = PrimaryLevel, R = NoIndividualReset, T = NonTransient> {
+    inner: Pool<C, R, T>,
     level: L,
-    buffers: Vec<CommandBuffer>,
-    next: usize,
+    free: Vec<CommandBuffer>,
+    submitted: Vec<(CommandBuffer, Fence)>,
 }
 
-impl<C, L> OwningPool<C, L>
+impl<C, L, R, T> OwningPool<C, L, R, T>
 where
     C: Capability,
     L: Level,
+    R: Reset,
+    T: Transience,
 {
     /// Wrap simple pool into owning version.
-    /// 
+    ///
     /// # Safety
-    /// 
+    ///
     /// * All buffers allocated from this pool must be [freed](#method.free_buffers).
-    pub unsafe fn from_inner(inner: Pool<C>, level: L) -> Self {
+    pub unsafe fn from_inner(inner: Pool<C, R, T>, level: L) -> Self {
         OwningPool {
             inner,
             level,
-            buffers: Vec::new(),
-            next: 0,
+            free: Vec::new(),
+            submitted: Vec::new(),
         }
     }
 
-    /// Reserve at least `count` buffers.
+    /// Reserve at least `count` buffers on the free list.
     /// Allocate if there are not enough unused buffers.
     pub fn reserve(&mut self, device: &impl DeviceV1_0, count: usize) {
-        let total = self.next + count;
-        if total >= self.buffers.len() {
-            let add = total - self.buffers.len();
+        if count > self.free.len() {
+            let add = count - self.free.len();
 
             // TODO: avoid Vec allocation.
-            self.buffers.extend(unsafe {
+            self.free.extend(unsafe {
                 device.allocate_command_buffers(
                     &CommandBufferAllocateInfo::builder()
                         .command_pool(self.inner.raw)
@@ -188,20 +302,26 @@ where
         }
     }
 
-    /// Acquire next unused command buffer from pool.
-    /// 
+    /// Acquire an unused command buffer, popping from the free list and only
+    /// allocating a fresh one when the free list is empty.
+    ///
     /// # Safety
-    /// 
+    ///
     /// * Acquired buffer must be [released](struct.Buffer#method.release) when no longer needed.
     pub fn acquire_buffer(
         &mut self,
         device: &impl DeviceV1_0,
-    ) -> Buffer<C, InitialState, L> {
-        self.reserve(device, 1);
-        self.next += 1;
+    ) -> Buffer<C, InitialState, L, R> {
+        let raw = match self.free.pop() {
+            Some(raw) => raw,
+            None => {
+                self.reserve(device, 1);
+                self.free.pop().expect("Reserve guarantees a free buffer")
+            }
+        };
         unsafe {
             Buffer::from_raw(
-                self.buffers[self.next - 1],
+                raw,
                 self.inner.capability,
                 InitialState,
                 self.level,
@@ -211,39 +331,102 @@ where
         }
     }
 
-    /// Reset all buffers at once.
-    /// [`Pool::acquire_buffer`](#method.acquire_buffer) will reuse allocated buffers.
-    ///
-    /// # Safety
+    /// Keep `resource` alive until the pool is reset.
+    /// See [`Pool::hold`](struct.Pool.html#method.hold).
+    pub fn hold(&mut self, resource: Arc<dyn Any + Send + Sync>) {
+        self.inner.hold(resource);
+    }
+
+    /// Reset all buffers at once, dropping every held resource.
+    /// [`OwningPool::acquire_buffer`](#method.acquire_buffer) will reuse allocated buffers.
+    /// Fails if any buffer is still pending execution.
     ///
-    /// * All buffers acquired from this pool must be released.
-    /// * Commands in buffers must be [complete](struct.Buffer#method.complete).
-    /// 
     /// Note.
     /// * Any primary buffer that references secondary buffer from this pool will be invalidated.
-    pub unsafe fn reset(&mut self, device: &impl DeviceV1_0) {
-        self.inner.reset(device);
-        self.next = 0;
+    pub fn reset(&mut self, device: &impl DeviceV1_0) -> Result<(), SynchronizationError> {
+        self.inner.reset(device)?;
+        self.free.extend(self.submitted.drain(..).map(|(raw, _)| raw));
+        Ok(())
     }
 
     /// Dispose of command pool.
-    /// 
+    ///
     /// # Safety
-    /// 
-    /// Same as for [`Pool::reset`](#method.reset).
+    ///
+    /// * All buffers acquired from this pool must be released.
+    /// * Commands in buffers must be [complete](struct.Buffer#method.complete).
     pub unsafe fn dispose(mut self, device: &impl DeviceV1_0) {
-        self.reset(device);
-        if !self.buffers.is_empty() {
-            device.free_command_buffers(self.inner.raw, &self.buffers);
+        let mut all = std::mem::replace(&mut self.free, Vec::new());
+        all.extend(self.submitted.drain(..).map(|(raw, _)| raw));
+        if !all.is_empty() {
+            device.free_command_buffers(self.inner.raw, &all);
         }
 
+        self.inner.pending = 0;
         self.inner.dispose(device);
     }
 }
 
-impl<L> OwningPool<QueueFlags, L> {
+impl<C, L, T> OwningPool<C, L, IndividualReset, T>
+where
+    C: Capability,
+    L: Level,
+    T: Transience,
+{
+    /// Produce a `Submit` from a one-shot `buffer` acquired from this pool and
+    /// register it as submitted with `fence`.
+    /// It will be recycled back onto the free list by [`recycle`](#method.recycle)
+    /// once the fence is signaled.
+    ///
+    /// Bound to [`IndividualReset`]: [`recycle`](#method.recycle) is the only
+    /// way `pending` is decremented for buffers submitted here, and `recycle`
+    /// itself requires [`IndividualReset`] to reset buffers one at a time.
+    ///
+    /// # Safety
+    ///
+    /// * `buffer` must have been [acquired](#method.acquire_buffer) from this pool.
+    pub unsafe fn submit(
+        &mut self,
+        buffer: Buffer<C, ExecutableState<OneShot>, L, IndividualReset>,
+        fence: Fence,
+    ) -> Submit<L>
+    where
+        L: Level,
+    {
+        let (submit, buffer) = self.inner.submit_once(buffer);
+        let raw = buffer.raw();
+        buffer.release();
+        self.submitted.push((raw, fence));
+        submit
+    }
+
+    /// Recycle submitted buffers whose fences have signaled back onto the free list.
+    /// Each signaled buffer is reset individually (hence [`IndividualReset`]);
+    /// still-pending buffers are left in place.
+    pub fn recycle(&mut self, device: &impl DeviceV1_0) {
+        let mut index = 0;
+        while index < self.submitted.len() {
+            let (raw, fence) = self.submitted[index];
+            match unsafe { device.get_fence_status(fence) } {
+                Ok(()) => {
+                    unsafe {
+                        device.reset_command_buffer(raw, CommandBufferResetFlags::empty())
+                            .expect("Panic on OOM");
+                    }
+                    self.submitted.swap_remove(index);
+                    self.inner.completed();
+                    self.free.push(raw);
+                }
+                Err(ash::vk::Result::NOT_READY) => index += 1,
+                Err(error) => panic!("Panic on fence status error: {:?}", error),
+            }
+        }
+    }
+}
+
+impl<L, R, T> OwningPool<QueueFlags, L, R, T> {
     /// Convert capability level.
-    pub fn from_flags<C>(self) -> Result<OwningPool<C, L>, Self>
+    pub fn from_flags<C>(self) -> Result<OwningPool<C, L, R, T>, Self>
     where
         C: Capability,
     {
@@ -251,14 +434,14 @@ impl<L> OwningPool<QueueFlags, L> {
             Ok(inner) => Ok(OwningPool {
                 inner,
                 level: self.level,
-                buffers: self.buffers,
-                next: self.next,
+                free: self.free,
+                submitted: self.submitted,
             }),
             Err(inner) => Err(OwningPool {
                 inner,
                 level: self.level,
-                buffers: self.buffers,
-                next: self.next,
+                free: self.free,
+                submitted: self.submitted,
             }),
         }
     }
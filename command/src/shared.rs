@@ -0,0 +1,188 @@
+//! Shared pool module docs.
+
+use std::{
+    any::Any,
+    collections::hash_map::Entry,
+    sync::{Arc, Mutex, Weak},
+    thread::{self, ThreadId},
+};
+
+use ash::{
+    version::DeviceV1_0,
+    vk::{CommandBuffer, CommandBufferResetFlags, QueueFlags},
+};
+
+use crossbeam_queue::SegQueue;
+use fnv::FnvHashMap;
+
+use crate::{
+    buffer::{Buffer, IndividualReset, InitialState, PrimaryLevel, Resettable},
+    capability::Capability,
+    family::FamilyIndex,
+    pool::Pool,
+};
+
+/// One underlying `Pool` per thread plus a lock-free free list of its recycled
+/// buffers. Vulkan command pools are not thread-safe, so the `Pool` itself is
+/// only ever touched under its own local lock, while the free list can be
+/// pushed to without the global map lock.
+struct ThreadPool<C> {
+    pool: Mutex<Pool<C, IndividualReset>>,
+    free: SegQueue<CommandBuffer>,
+}
+
+/// Command buffer handed out by a [`SharedPool`].
+/// Carries a weak reference back to its originating per-thread pool so that
+/// [releasing](#method.release) it recycles the handle without taking the
+/// global lock.
+pub struct SharedBuffer<C, S> {
+    buffer: Buffer<C, S, PrimaryLevel, IndividualReset>,
+    origin: Weak<ThreadPool<C>>,
+}
+
+impl<C, S> SharedBuffer<C, S> {
+    /// Borrow the wrapped command buffer.
+    pub fn buffer(&mut self) -> &mut Buffer<C, S, PrimaryLevel, IndividualReset> {
+        &mut self.buffer
+    }
+
+    /// Keep `resource` alive until the originating thread pool is disposed
+    /// by [`SharedPool::dispose`]; per-thread pools are never reset.
+    /// See [`Pool::hold`](../pool/struct.Pool.html#method.hold).
+    /// No-op if the originating pool is already gone.
+    pub fn hold(&self, resource: Arc<dyn Any + Send + Sync>) {
+        if let Some(origin) = self.origin.upgrade() {
+            origin.pool.lock().unwrap().hold(resource);
+        }
+    }
+
+    /// Release the buffer, recycling it onto its originating thread's free list.
+    ///
+    /// # Safety
+    ///
+    /// * Commands recorded to this buffer must be [complete](../buffer/struct.Buffer.html#method.complete).
+    pub unsafe fn release(self, device: &impl DeviceV1_0)
+    where
+        S: Resettable,
+    {
+        let raw = self.buffer.into_raw();
+        if let Some(origin) = self.origin.upgrade() {
+            device.reset_command_buffer(raw, CommandBufferResetFlags::empty())
+                .expect("Panic on OOM");
+            origin.free.push(raw);
+        }
+        // If the originating pool is gone the handle was freed with it.
+    }
+}
+
+/// Thread-sharded command pool modeled on vulkano's standard command pool.
+/// Keeps one [`Pool`] per thread behind a global lock. Every acquisition takes
+/// that global lock briefly to look up the calling thread's shard, then pops
+/// from the shard's lock-free free list or allocates under the shard's own lock.
+/// Releasing a buffer pushes it onto its shard's free list without the global lock.
+pub struct SharedPool<C = QueueFlags> {
+    pools: Mutex<FnvHashMap<ThreadId, Arc<ThreadPool<C>>>>,
+    create: Box<dyn Fn() -> Pool<C, IndividualReset> + Send + Sync>,
+    capability: C,
+    family: FamilyIndex,
+}
+
+impl<C> SharedPool<C>
+where
+    C: Capability + Copy,
+{
+    /// Create a shared pool.
+    /// `create` is invoked once per worker thread to build that thread's
+    /// underlying pool; it must produce a pool created for `family` with the
+    /// individual-reset flag set.
+    pub fn new<F>(capability: C, family: FamilyIndex, create: F) -> Self
+    where
+        F: Fn() -> Pool<C, IndividualReset> + Send + Sync + 'static,
+    {
+        SharedPool {
+            pools: Mutex::new(FnvHashMap::default()),
+            create: Box::new(create),
+            capability,
+            family,
+        }
+    }
+
+    /// Acquire a command buffer from the calling thread's shard.
+    /// Reuses a recycled buffer when one is available, otherwise allocates from
+    /// that thread's underlying pool.
+    ///
+    /// # Safety
+    ///
+    /// * Acquired buffer must be [released](struct.SharedBuffer.html#method.release) when no longer needed.
+    pub fn acquire_buffer(&self, device: &impl DeviceV1_0) -> SharedBuffer<C, InitialState> {
+        let thread_pool = {
+            let mut pools = self.pools.lock().unwrap();
+            match pools.entry(thread::current().id()) {
+                Entry::Occupied(entry) => Arc::clone(entry.get()),
+                Entry::Vacant(entry) => {
+                    let thread_pool = Arc::new(ThreadPool {
+                        pool: Mutex::new((self.create)()),
+                        free: SegQueue::new(),
+                    });
+                    entry.insert(Arc::clone(&thread_pool));
+                    thread_pool
+                }
+            }
+        };
+
+        let buffer = match thread_pool.free.pop() {
+            Some(raw) => unsafe {
+                Buffer::from_raw(
+                    raw,
+                    self.capability,
+                    InitialState,
+                    PrimaryLevel,
+                    IndividualReset,
+                    self.family,
+                )
+            },
+            None => thread_pool
+                .pool
+                .lock()
+                .unwrap()
+                .allocate_buffers(device, PrimaryLevel, 1)
+                .pop()
+                .expect("One buffer was requested"),
+        };
+
+        SharedBuffer {
+            buffer,
+            origin: Arc::downgrade(&thread_pool),
+        }
+    }
+
+    /// Dispose of every per-thread pool.
+    ///
+    /// # Safety
+    ///
+    /// * All buffers acquired from this pool must be [released](struct.SharedBuffer.html#method.release).
+    pub unsafe fn dispose(self, device: &impl DeviceV1_0) {
+        for (_, thread_pool) in self.pools.into_inner().unwrap() {
+            let thread_pool = Arc::try_unwrap(thread_pool).unwrap_or_else(|_| {
+                panic!("All SharedBuffers from this pool must be released before dispose")
+            });
+
+            let free = std::iter::from_fn(|| thread_pool.free.pop())
+                .map(|raw| {
+                    Buffer::from_raw(
+                        raw,
+                        self.capability,
+                        InitialState,
+                        PrimaryLevel,
+                        IndividualReset,
+                        self.family,
+                    )
+                })
+                .collect();
+
+            let mut pool = thread_pool.pool.into_inner().unwrap();
+            pool.free_buffers(device, free);
+            pool.dispose(device);
+        }
+    }
+}